@@ -1,18 +1,91 @@
+use std::rc::Rc;
 use serde::{ Serialize, Deserialize };
 use libtetris::*;
 use rand::prelude::*;
 use crate::{ Controller, GameConfig };
 
 pub struct Game {
-    pub board: Board<ColoredRow>,
+    /// Reference-counted so `snapshot`/`restore` can hand out and swap in
+    /// copies in O(1) (a refcount bump) instead of deep-cloning; mutation
+    /// goes through `Rc::make_mut`, which only pays for an actual deep copy
+    /// the first time a shared board diverges after a restore, not on every
+    /// restore call.
+    pub board: Rc<Board<ColoredRow>>,
     state: GameState,
     config: GameConfig,
     did_hold: bool,
     prev: Controller,
     used: Controller,
-    das_delay: u32,
+    direction: i32,
+    das_left: f32,
+    arr_left: f32,
     pub garbage_queue: u32,
-    attacking: u32
+    attacking: u32,
+    /// Current combo count, or `-1` if the last placement didn't clear a line.
+    pub combo: i32,
+    pub back_to_back: bool,
+    pub score: u64,
+    pub pieces_placed: u32,
+    pub lines_cleared: u32,
+    /// Marathon-style level, derived from `lines_cleared` (one level per 10
+    /// lines, starting at 1); scales the score awarded per clear.
+    pub level: u32,
+    pub ticks: u32
+}
+
+/// The kind of line clear a placement produced, as reported in
+/// `Event::LineCleared`.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ClearKind {
+    Single,
+    Double,
+    Triple,
+    Tetris,
+    TspinMiniSingle,
+    TspinMiniDouble,
+    TspinSingle,
+    TspinDouble,
+    TspinTriple
+}
+
+impl ClearKind {
+    fn from_lock(tspin: TspinStatus, lines: usize) -> Self {
+        match (tspin, lines) {
+            (TspinStatus::None, 1) => ClearKind::Single,
+            (TspinStatus::None, 2) => ClearKind::Double,
+            (TspinStatus::None, 3) => ClearKind::Triple,
+            (TspinStatus::Mini, 1) => ClearKind::TspinMiniSingle,
+            (TspinStatus::Mini, 2) => ClearKind::TspinMiniDouble,
+            (TspinStatus::Full, 1) => ClearKind::TspinSingle,
+            (TspinStatus::Full, 2) => ClearKind::TspinDouble,
+            (TspinStatus::Full, 3) => ClearKind::TspinTriple,
+            _ => ClearKind::Tetris
+        }
+    }
+
+    /// Base guideline score for this clear, before combo/back-to-back bonuses.
+    fn base_score(self) -> u32 {
+        match self {
+            ClearKind::Single => 100,
+            ClearKind::Double => 300,
+            ClearKind::Triple => 500,
+            ClearKind::Tetris => 800,
+            ClearKind::TspinMiniSingle => 200,
+            ClearKind::TspinMiniDouble => 400,
+            ClearKind::TspinSingle => 800,
+            ClearKind::TspinDouble => 1200,
+            ClearKind::TspinTriple => 1600
+        }
+    }
+
+    /// Whether this clear extends (or starts) a back-to-back streak. Per
+    /// guideline, any T-spin clear (mini or full) qualifies, not just
+    /// Tetrises and full T-spins.
+    fn is_difficult(self) -> bool {
+        matches!(self,
+            ClearKind::Tetris | ClearKind::TspinMiniSingle | ClearKind::TspinMiniDouble |
+            ClearKind::TspinSingle | ClearKind::TspinDouble | ClearKind::TspinTriple)
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -34,14 +107,90 @@ pub enum Event {
     },
     GarbageSent(u32),
     GarbageAdded(Vec<usize>),
-    GameOver
+    ModeProgress {
+        ticks: u32,
+        pieces_placed: u32,
+        lines_cleared: u32
+    },
+    LineCleared {
+        combo: i32,
+        b2b: bool,
+        clear_kind: ClearKind,
+        score_delta: u32
+    },
+    GameOver(LossReason)
 }
 
+/// The reason a game ended, as reported in `Event::GameOver`.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LossReason {
+    /// The next piece (or the piece pulled out of hold) had nowhere to spawn.
+    BlockOut,
+    /// A piece locked with part of it above the visible playfield.
+    LockOut,
+    /// Incoming garbage filled the board past the top.
+    Garbage,
+    /// The configured piece limit for this game mode was reached.
+    PieceLimitReached,
+    /// The configured line goal for this game mode was reached.
+    LineGoalReached,
+    /// The configured time/tick budget for this game mode ran out.
+    TimeExpired
+}
+
+/// Governs whether a `Game` runs forever or terminates against an objective.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum GameMode {
+    /// Play until topped out; no objective-based termination.
+    Endless,
+    /// End once this many pieces have been placed (e.g. marathon).
+    PieceLimit(u32),
+    /// End once this many total lines have been cleared (e.g. 40-line sprint).
+    LineGoal(u32),
+    /// End once this many `update` calls have elapsed (e.g. ultra/time attack).
+    TimeLimit(u32)
+}
+
+impl Default for GameMode {
+    fn default() -> Self {
+        GameMode::Endless
+    }
+}
+
+#[derive(Copy, Clone)]
 enum GameState {
     SpawnDelay(u32),
     LineClearDelay(u32),
     Falling(FallingState),
-    GameOver
+    GameOver(LossReason)
+}
+
+/// A point-in-time copy of a `Game`'s state, taken with `Game::snapshot` and
+/// restored with `Game::restore`.
+///
+/// This does *not* capture the `piece_rng`/`garbage_rng` streams passed into
+/// `Game::update` - since those live outside `Game`, callers that want a
+/// snapshot to replay deterministically (rollback netcode, search) must
+/// snapshot and restore their own clonable RNG state alongside this.
+#[derive(Clone)]
+pub struct GameSnapshot {
+    board: Rc<Board<ColoredRow>>,
+    state: GameState,
+    did_hold: bool,
+    prev: Controller,
+    used: Controller,
+    direction: i32,
+    das_left: f32,
+    arr_left: f32,
+    garbage_queue: u32,
+    attacking: u32,
+    combo: i32,
+    back_to_back: bool,
+    score: u64,
+    pieces_placed: u32,
+    lines_cleared: u32,
+    level: u32,
+    ticks: u32
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -51,7 +200,7 @@ struct FallingState {
     rotation_move_count: u32,
     gravity: i32,
     lock_delay: u32,
-    soft_drop_delay: u32
+    soft_drop_delay: f32
 }
 
 impl Game {
@@ -61,22 +210,29 @@ impl Game {
             board.add_next_piece(board.generate_next_piece(piece_rng));
         }
         Game {
-            board, config,
+            board: Rc::new(board), config,
             prev: Default::default(),
             used: Default::default(),
             did_hold: false,
-            das_delay: config.delayed_auto_shift,
+            direction: 0,
+            das_left: 0.0,
+            arr_left: 0.0,
             state: GameState::SpawnDelay(config.spawn_delay),
             garbage_queue: 0,
-            attacking: 0
+            attacking: 0,
+            combo: -1,
+            back_to_back: false,
+            score: 0,
+            pieces_placed: 0,
+            lines_cleared: 0,
+            level: 1,
+            ticks: 0
         }
     }
 
     pub fn update(
         &mut self, current: Controller, piece_rng: &mut impl Rng, garbage_rng: &mut impl Rng
     ) -> Vec<Event> {
-        update_input(&mut self.used.left, self.prev.left, current.left);
-        update_input(&mut self.used.right, self.prev.right, current.right);
         update_input(&mut self.used.rotate_right, self.prev.rotate_right, current.rotate_right);
         update_input(&mut self.used.rotate_left, self.prev.rotate_left, current.rotate_left);
         update_input(&mut self.used.soft_drop, self.prev.soft_drop, current.soft_drop);
@@ -86,44 +242,76 @@ impl Game {
 
         let switched_left_right = (current.left != self.prev.left) &&
             (current.right != self.prev.right);
+        let held_direction = if current.left == current.right || switched_left_right {
+            0
+        } else if current.left {
+            -1
+        } else {
+            1
+        };
 
-        if current.left != current.right && !switched_left_right {
-            if self.used.left || self.used.right {
-                // While movement is buffered, don't let the time
-                // until the next shift fall below the auto-repeat rate.
-                // Otherwise we might rapidly shift twice when a piece spawns.
-                if self.das_delay > self.config.auto_repeat_rate {
-                    self.das_delay -= 1;
-                }
-            } else if self.das_delay == 0 {
-                // Apply auto-shift
-                self.das_delay = self.config.auto_repeat_rate;
-                self.used.left = current.left;
-                self.used.right = current.right;
+        // Number of auto-shifts to apply this update (and whether to slide
+        // all the way to the wall instead, for an ARR of 0).
+        let mut shifts = 0u32;
+        let mut instant_shift = false;
+
+        if held_direction != self.direction {
+            // Direction pressed, released, or reversed: the initial tap is
+            // handled below as an edge-triggered shift, so just (re)arm DAS.
+            self.direction = held_direction;
+            let seed = self.config.das - 1.0;
+            if seed <= 0.0 {
+                // DAS is already satisfied within the press frame itself
+                // (e.g. das == 0): load the ARR clock now so the overshoot
+                // is only ever folded in once, by the next held frame's own
+                // -1.0 drain below, instead of compounding with it here.
+                self.das_left = 0.0;
+                self.arr_left = self.config.arr;
             } else {
-                self.das_delay -= 1;
+                self.das_left = seed;
+                self.arr_left = 0.0;
             }
+            self.used.left = held_direction == -1;
+            self.used.right = held_direction == 1;
         } else {
-            // Reset delayed auto shift
-            self.das_delay = self.config.delayed_auto_shift;
             self.used.left = false;
             self.used.right = false;
-
-            // Redo button presses
-            if current.left && !self.prev.left {
-                self.used.left = true;
-            } else if current.right && !self.prev.right {
-                self.used.right = true;
+            if held_direction != 0 {
+                self.das_left -= 1.0;
+                if self.das_left <= 0.0 {
+                    self.arr_left += self.das_left;
+                    self.das_left = 0.0;
+                    if self.config.arr == 0.0 {
+                        instant_shift = true;
+                    } else {
+                        while self.arr_left <= 0.0 {
+                            shifts += 1;
+                            self.arr_left += self.config.arr;
+                        }
+                    }
+                }
             }
         }
 
         self.prev = current;
 
+        self.ticks += 1;
+        if let GameMode::TimeLimit(budget) = self.config.mode {
+            if self.ticks >= budget && !matches!(self.state, GameState::GameOver(_)) {
+                self.state = GameState::GameOver(LossReason::TimeExpired);
+                return vec![Event::GameOver(LossReason::TimeExpired)];
+            }
+        }
+
         match self.state {
             GameState::SpawnDelay(0) => {
-                let next_piece = self.board.advance_queue().unwrap();
-                let new_piece = self.board.generate_next_piece(piece_rng);
-                self.board.add_next_piece(new_piece);
+                let (next_piece, new_piece) = {
+                    let board = Rc::make_mut(&mut self.board);
+                    let next_piece = board.advance_queue().unwrap();
+                    let new_piece = board.generate_next_piece(piece_rng);
+                    board.add_next_piece(new_piece);
+                    (next_piece, new_piece)
+                };
                 if let Some(spawned) = FallingPiece::spawn(next_piece, &self.board) {
                     self.state = GameState::Falling(FallingState {
                         piece: spawned,
@@ -131,7 +319,7 @@ impl Game {
                         rotation_move_count: 0,
                         gravity: self.config.gravity,
                         lock_delay: 30,
-                        soft_drop_delay: 0
+                        soft_drop_delay: 0.0
                     });
                     let mut ghost = spawned;
                     ghost.sonic_drop(&self.board);
@@ -140,8 +328,8 @@ impl Game {
                         Event::PieceFalling(spawned, ghost)
                     ]
                 } else {
-                    self.state = GameState::GameOver;
-                    vec![Event::GameOver]
+                    self.state = GameState::GameOver(LossReason::BlockOut);
+                    vec![Event::GameOver(LossReason::BlockOut)]
                 }
             }
             GameState::SpawnDelay(ref mut delay) => {
@@ -162,7 +350,7 @@ impl Game {
                 *delay -= 1;
                 vec![]
             }
-            GameState::GameOver => vec![Event::GameOver],
+            GameState::GameOver(reason) => vec![Event::GameOver(reason)],
             GameState::Falling(ref mut falling) => {
                 let mut events = vec![];
                 let was_on_stack = self.board.on_stack(&falling.piece);
@@ -171,7 +359,7 @@ impl Game {
                 if !self.did_hold && self.used.hold {
                     self.did_hold = true;
                     events.push(Event::PieceHeld(falling.piece.kind.0));
-                    if let Some(piece) = self.board.hold(falling.piece.kind.0) {
+                    if let Some(piece) = Rc::make_mut(&mut self.board).hold(falling.piece.kind.0) {
                         // Piece in hold; the piece spawns instantly
                         if let Some(spawned) = FallingPiece::spawn(piece, &self.board) {
                             *falling = FallingState {
@@ -180,15 +368,15 @@ impl Game {
                                 rotation_move_count: 0,
                                 gravity: self.config.gravity,
                                 lock_delay: 30,
-                                soft_drop_delay: 0
+                                soft_drop_delay: 0.0
                             };
                             let mut ghost = spawned;
                             ghost.sonic_drop(&self.board);
                             events.push(Event::PieceFalling(spawned, ghost));
                         } else {
                             // Hold piece couldn't spawn; Block Out
-                            self.state = GameState::GameOver;
-                            events.push(Event::GameOver);
+                            self.state = GameState::GameOver(LossReason::BlockOut);
+                            events.push(Event::GameOver(LossReason::BlockOut));
                         }
                     } else {
                         // Nothing in hold; spawn next piece normally
@@ -224,22 +412,28 @@ impl Game {
                 }
 
                 // Shift
+                let mut shifted = false;
                 if self.used.left {
-                    if falling.piece.shift(&self.board, -1, 0) {
-                        self.used.left = false;
-                        falling.rotation_move_count += 1;
-                        falling.lock_delay = self.config.lock_delay;
-                        events.push(Event::PieceMoved);
-                    }
+                    shifted |= falling.piece.shift(&self.board, -1, 0);
                 }
                 if self.used.right {
-                    if falling.piece.shift(&self.board, 1, 0) {
-                        self.used.right = false;
-                        falling.rotation_move_count += 1;
-                        falling.lock_delay = self.config.lock_delay;
-                        events.push(Event::PieceMoved);
+                    shifted |= falling.piece.shift(&self.board, 1, 0);
+                }
+                if instant_shift {
+                    // ARR of 0: slide all the way to the wall in one update.
+                    while falling.piece.shift(&self.board, self.direction, 0) {
+                        shifted = true;
+                    }
+                } else {
+                    for _ in 0..shifts {
+                        shifted |= falling.piece.shift(&self.board, self.direction, 0);
                     }
                 }
+                if shifted {
+                    falling.rotation_move_count += 1;
+                    falling.lock_delay = self.config.lock_delay;
+                    events.push(Event::PieceMoved);
+                }
 
                 // 15 move lock rule reset
                 let low_y = falling.piece.cells().into_iter().map(|(_,y,_)| y).min().unwrap();
@@ -296,24 +490,39 @@ impl Game {
 
                     if self.board.on_stack(&falling.piece) {
                         events.push(Event::StackTouched);
-                    } else if self.config.gravity > self.config.soft_drop_speed as i32 * 100 {
-                        // Soft drop
-                        if self.used.soft_drop {
-                            if falling.soft_drop_delay == 0 {
-                                falling.piece.shift(&self.board, 0, -1);
-                                falling.soft_drop_delay = self.config.soft_drop_speed;
+                    } else if self.used.soft_drop &&
+                        (self.config.sdf == 0.0 || self.config.sdf > 1.0)
+                    {
+                        // Soft drop applies only for an sdf above 1.0 or for
+                        // the sonic-drop sentinel sdf == 0; with any other sdf
+                        // it has no effect. The check does not compare the
+                        // soft-drop rate against the configured gravity.
+                        if self.config.sdf == 0.0 {
+                            // SDF of 0: sonic drop to the stack without locking.
+                            let y = falling.piece.y;
+                            falling.piece.sonic_drop(&self.board);
+                            if falling.piece.y != y {
                                 falling.gravity = self.config.gravity;
                                 events.push(Event::PieceMoved);
-                                if self.board.on_stack(&falling.piece) {
-                                    events.push(Event::StackTouched);
-                                }
                                 events.push(Event::SoftDropped);
-                            } else {
-                                falling.soft_drop_delay -= 1;
                             }
+                            if self.board.on_stack(&falling.piece) {
+                                events.push(Event::StackTouched);
+                            }
+                        } else if falling.soft_drop_delay <= 0.0 {
+                            falling.piece.shift(&self.board, 0, -1);
+                            falling.soft_drop_delay += 1.0 / self.config.sdf;
+                            falling.gravity = self.config.gravity;
+                            events.push(Event::PieceMoved);
+                            if self.board.on_stack(&falling.piece) {
+                                events.push(Event::StackTouched);
+                            }
+                            events.push(Event::SoftDropped);
                         } else {
-                            falling.soft_drop_delay = 0;
+                            falling.soft_drop_delay -= 1.0;
                         }
+                    } else {
+                        falling.soft_drop_delay = 0.0;
                     }
                 }
 
@@ -321,11 +530,71 @@ impl Game {
                 ghost.sonic_drop(&self.board);
                 events.push(Event::PieceFalling(falling.piece, ghost));
 
+                events.push(Event::ModeProgress {
+                    ticks: self.ticks,
+                    pieces_placed: self.pieces_placed,
+                    lines_cleared: self.lines_cleared
+                });
+
                 events
             }
         }
     }
 
+    /// Captures the full state needed to resume this game later with
+    /// `restore`. The board is reference-counted, so this is just a
+    /// refcount bump, not a deep copy - same for `restore`. The first
+    /// in-place mutation of a shared board after a restore pays for one
+    /// deep copy (via `Rc::make_mut`'s copy-on-write), but repeated
+    /// restores to the same snapshot never do.
+    pub fn snapshot(&self) -> GameSnapshot {
+        GameSnapshot {
+            board: self.board.clone(),
+            state: self.state,
+            did_hold: self.did_hold,
+            prev: self.prev,
+            used: self.used,
+            direction: self.direction,
+            das_left: self.das_left,
+            arr_left: self.arr_left,
+            garbage_queue: self.garbage_queue,
+            attacking: self.attacking,
+            combo: self.combo,
+            back_to_back: self.back_to_back,
+            score: self.score,
+            pieces_placed: self.pieces_placed,
+            lines_cleared: self.lines_cleared,
+            level: self.level,
+            ticks: self.ticks
+        }
+    }
+
+    /// Rewinds this game to a previously captured `GameSnapshot`. Takes the
+    /// snapshot by reference - combined with the board's `Rc`, this is O(1)
+    /// (a refcount bump, not a deep copy), so a search/rollback loop can
+    /// restore to the same branch point as many times as it needs; the
+    /// restore itself never clones the board, but while the snapshot still
+    /// holds it, the first board mutation after each restore makes one copy.
+    pub fn restore(&mut self, snapshot: &GameSnapshot) {
+        self.board = snapshot.board.clone();
+        self.state = snapshot.state;
+        self.did_hold = snapshot.did_hold;
+        self.prev = snapshot.prev;
+        self.used = snapshot.used;
+        self.direction = snapshot.direction;
+        self.das_left = snapshot.das_left;
+        self.arr_left = snapshot.arr_left;
+        self.garbage_queue = snapshot.garbage_queue;
+        self.attacking = snapshot.attacking;
+        self.combo = snapshot.combo;
+        self.back_to_back = snapshot.back_to_back;
+        self.score = snapshot.score;
+        self.pieces_placed = snapshot.pieces_placed;
+        self.lines_cleared = snapshot.lines_cleared;
+        self.level = snapshot.level;
+        self.ticks = snapshot.ticks;
+    }
+
     fn lock(
         &mut self,
         falling: FallingState,
@@ -334,7 +603,8 @@ impl Game {
         dist: Option<i32>
     ) {
         self.did_hold = false;
-        let locked = self.board.lock_piece(falling.piece);;
+        let locked = Rc::make_mut(&mut self.board).lock_piece(falling.piece);
+        self.pieces_placed += 1;
 
         events.push(Event::PiecePlaced {
             piece: falling.piece,
@@ -343,15 +613,66 @@ impl Game {
         });
 
         if locked.locked_out {
-            self.state = GameState::GameOver;
-            events.push(Event::GameOver);
+            self.state = GameState::GameOver(LossReason::LockOut);
+            events.push(Event::GameOver(LossReason::LockOut));
         } else if locked.cleared_lines.is_empty() {
+            self.combo = -1;
             self.state = GameState::SpawnDelay(self.config.spawn_delay);
             self.deal_garbage(events, garbage_rng);
         } else {
-            self.attacking += locked.garbage_sent;
+            let kind = ClearKind::from_lock(falling.piece.tspin, locked.cleared_lines.len());
+            self.combo += 1;
+            self.lines_cleared += locked.cleared_lines.len() as u32;
+            self.level = 1 + self.lines_cleared / 10;
+
+            let mut score_delta = kind.base_score();
+            if kind.is_difficult() && self.back_to_back {
+                score_delta += score_delta / 2;
+            }
+            if self.combo > 0 {
+                score_delta += self.combo as u32 * 50;
+            }
+            score_delta *= self.level;
+            self.score += score_delta as u64;
+
+            let mut garbage = locked.garbage_sent;
+            if kind.is_difficult() && self.back_to_back {
+                garbage += 1;
+            }
+            if self.combo > 0 {
+                garbage += (self.combo as u32 + 1) / 2;
+            }
+            self.back_to_back = kind.is_difficult();
+            self.attacking += garbage;
+
+            events.push(Event::LineCleared {
+                combo: self.combo,
+                b2b: self.back_to_back,
+                clear_kind: kind,
+                score_delta
+            });
             self.state = GameState::LineClearDelay(self.config.line_clear_delay);
         }
+
+        if !matches!(self.state, GameState::GameOver(_)) {
+            match self.config.mode {
+                GameMode::PieceLimit(limit) if self.pieces_placed >= limit => {
+                    self.state = GameState::GameOver(LossReason::PieceLimitReached);
+                    events.push(Event::GameOver(LossReason::PieceLimitReached));
+                }
+                GameMode::LineGoal(goal) if self.lines_cleared >= goal => {
+                    self.state = GameState::GameOver(LossReason::LineGoalReached);
+                    events.push(Event::GameOver(LossReason::LineGoalReached));
+                }
+                _ => {}
+            }
+        }
+
+        events.push(Event::ModeProgress {
+            ticks: self.ticks,
+            pieces_placed: self.pieces_placed,
+            lines_cleared: self.lines_cleared
+        });
     }
 
     fn deal_garbage(&mut self, events: &mut Vec<Event>, rng: &mut impl Rng) {
@@ -371,13 +692,13 @@ impl Game {
                     col = rng.gen_range(0, 10);
                 }
                 garbage_columns.push(col);
-                dead |= self.board.add_garbage(col);
+                dead |= Rc::make_mut(&mut self.board).add_garbage(col);
             }
             self.garbage_queue -= self.garbage_queue.min(self.config.max_garbage_add);
             events.push(Event::GarbageAdded(garbage_columns));
             if dead {
-                events.push(Event::GameOver);
-                self.state = GameState::GameOver;
+                events.push(Event::GameOver(LossReason::Garbage));
+                self.state = GameState::GameOver(LossReason::Garbage);
             }
         } else if self.attacking > 0 {
             events.push(Event::GarbageSent(self.attacking));
@@ -2,13 +2,16 @@ use tttz_mpboard::Game;
 use tttz_ai::CCBot;
 use tttz_ai::Thinker;
 use cold_clear::Interface;
-use cold_clear::evaluation::Standard;
+use cold_clear::evaluation::Evaluator;
+use battle::{ Game as BattleGame, GameConfig, GameMode, Event, LossReason };
+use crate::nn_evaluator::{ self, FEATURE_COUNT };
+use rand::SeedableRng;
 
-pub fn do_battle(
-    p1: Standard, p2: Standard,
+pub fn do_battle<E1: Evaluator, E2: Evaluator>(
+	p1: E1, p2: E2,
 ) -> Option<((), bool)> {
 	let mut game = Game::new(1, 2, [].iter());
-	let mut bots = [
+	let mut bots = (
 		CCBot {
 			interface: Interface::launch(
 				libtetris::Board::new(),
@@ -27,11 +30,16 @@ pub fn do_battle(
 			),
 			preview_list: [7; 6],
 		},
-	];
+	);
 	let mut player = 0; // current player index
 	loop {
 		let display = game.generate_display(player, 0);
-		for key_type in bots[player].main_think(display).into_iter() {
+		let key_types = if player == 0 {
+			bots.0.main_think(display)
+		} else {
+			bots.1.main_think(display)
+		};
+		for key_type in key_types.into_iter() {
 			let ret = game.process_key(player as i32 + 1, 0, key_type).0;
 			if ret > 0 {
 				return Some(((), ret == 1))
@@ -40,3 +48,124 @@ pub fn do_battle(
 		player = 1 - player;
 	}
 }
+
+/// Races two bots to clear `line_goal` lines each on independent boards and
+/// reports the winner by elapsed ticks (`true` if `p1` finished first), or
+/// `None` if neither reached the goal. Unlike `do_battle`, which drives
+/// `tttz_mpboard::Game` for head-to-head garbage warfare, this drives
+/// `battle::Game` with `GameMode::LineGoal` directly, so a 40-line sprint
+/// race actually exercises `GameMode`/`Event::ModeProgress` instead of them
+/// sitting unused behind the tttz harness.
+pub fn do_sprint_race<E1: Evaluator, E2: Evaluator>(
+	p1: E1, p2: E2, line_goal: u32,
+) -> Option<bool> {
+	let mut config = GameConfig::default();
+	config.mode = GameMode::LineGoal(line_goal);
+
+	// Both racers must see the same piece sequence, or the winner is
+	// confounded by who drew easier pieces rather than by evaluator skill.
+	// Seed one RNG and clone it into two independent streams so each side
+	// draws identically without sharing mutable state across the race.
+	let piece_seed = rand::rngs::StdRng::from_entropy();
+	let mut piece_rng1 = piece_seed.clone();
+	let mut piece_rng2 = piece_seed;
+	let mut garbage_rng1 = rand::rngs::StdRng::from_entropy();
+	let mut garbage_rng2 = rand::rngs::StdRng::from_entropy();
+	let mut game1 = BattleGame::new(config, &mut piece_rng1);
+	let mut game2 = BattleGame::new(config, &mut piece_rng2);
+
+	let bot1 = Interface::launch((*game1.board).clone(), Default::default(), p1, None);
+	let bot2 = Interface::launch((*game2.board).clone(), Default::default(), p2, None);
+
+	let mut result1 = None;
+	let mut result2 = None;
+
+	loop {
+		if result1.is_none() {
+			let controller = bot1.suggest_next_move(&game1.board);
+			for event in game1.update(controller, &mut piece_rng1, &mut garbage_rng1) {
+				if let Event::GameOver(reason) = event {
+					result1 = Some((game1.ticks, reason == LossReason::LineGoalReached));
+				}
+			}
+		}
+		if result2.is_none() {
+			let controller = bot2.suggest_next_move(&game2.board);
+			for event in game2.update(controller, &mut piece_rng2, &mut garbage_rng2) {
+				if let Event::GameOver(reason) = event {
+					result2 = Some((game2.ticks, reason == LossReason::LineGoalReached));
+				}
+			}
+		}
+
+		if let (Some((ticks1, done1)), Some((ticks2, done2))) = (result1, result2) {
+			return match (done1, done2) {
+				(true, true) => Some(ticks1 <= ticks2),
+				(true, false) => Some(true),
+				(false, true) => Some(false),
+				(false, false) => None
+			};
+		}
+	}
+}
+
+/// Plays one self-play battle between two evaluators, each on their own
+/// board, exchanging garbage the same way a real head-to-head match would:
+/// a `PiecePlaced` that sends garbage (`Event::GarbageSent`) on one side
+/// queues it onto the other's `garbage_queue`. Captures each side's feature
+/// vector after every placement (`Event::PiecePlaced`) along the way, and
+/// returns both sides' per-position trajectories plus which side survived
+/// longer, so the caller can train on individual positions instead of
+/// nudging towards a single whole-game result. `None` if both sides top out
+/// on the same tick.
+pub fn do_training_game<E1: Evaluator, E2: Evaluator>(
+	p1: E1, p2: E2,
+) -> Option<(Vec<[f32; FEATURE_COUNT]>, Vec<[f32; FEATURE_COUNT]>, bool)> {
+	let config = GameConfig::default();
+
+	let mut piece_rng = rand::thread_rng();
+	let mut garbage_rng = rand::thread_rng();
+	let mut game1 = BattleGame::new(config, &mut piece_rng);
+	let mut game2 = BattleGame::new(config, &mut piece_rng);
+
+	let bot1 = Interface::launch((*game1.board).clone(), Default::default(), p1, None);
+	let bot2 = Interface::launch((*game2.board).clone(), Default::default(), p2, None);
+
+	let mut positions1 = Vec::new();
+	let mut positions2 = Vec::new();
+	let mut survived1 = None;
+	let mut survived2 = None;
+
+	loop {
+		if survived1.is_none() {
+			let controller = bot1.suggest_next_move(&game1.board);
+			for event in game1.update(controller, &mut piece_rng, &mut garbage_rng) {
+				match event {
+					Event::PiecePlaced { .. } => positions1.push(nn_evaluator::features(
+						&game1.board, game1.garbage_queue, game1.combo, game1.back_to_back
+					)),
+					Event::GarbageSent(amount) => game2.garbage_queue += amount,
+					Event::GameOver(_) => survived1 = Some(game1.ticks),
+					_ => {}
+				}
+			}
+		}
+		if survived2.is_none() {
+			let controller = bot2.suggest_next_move(&game2.board);
+			for event in game2.update(controller, &mut piece_rng, &mut garbage_rng) {
+				match event {
+					Event::PiecePlaced { .. } => positions2.push(nn_evaluator::features(
+						&game2.board, game2.garbage_queue, game2.combo, game2.back_to_back
+					)),
+					Event::GarbageSent(amount) => game1.garbage_queue += amount,
+					Event::GameOver(_) => survived2 = Some(game2.ticks),
+					_ => {}
+				}
+			}
+		}
+
+		if let (Some(ticks1), Some(ticks2)) = (survived1, survived2) {
+			return if ticks1 == ticks2 { None } else { Some((positions1, positions2, ticks1 > ticks2)) };
+		}
+	}
+}
@@ -0,0 +1,136 @@
+use serde::{ Serialize, Deserialize };
+use cold_clear::evaluation::Evaluator;
+use libtetris::{ Board, ColoredRow, LockResult, Piece };
+
+// Column heights (10) + bumpiness (9) + hole count (1) + garbage queue (1) +
+// combo (1) + back-to-back (1).
+pub(crate) const FEATURE_COUNT: usize = 10 + 9 + 1 + 1 + 1 + 1;
+const HIDDEN_SIZE: usize = 32;
+
+/// A small fixed-size MLP's weights, kept serializable so training runs can
+/// resume.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NnWeights {
+	w1: Vec<[f32; FEATURE_COUNT]>,
+	b1: Vec<f32>,
+	w2: Vec<f32>,
+	b2: f32
+}
+
+impl NnWeights {
+	pub fn random(rng: &mut impl rand::Rng) -> Self {
+		NnWeights {
+			w1: (0..HIDDEN_SIZE)
+				.map(|_| {
+					let mut row = [0.0; FEATURE_COUNT];
+					for v in row.iter_mut() {
+						*v = rng.gen_range(-0.1, 0.1);
+					}
+					row
+				})
+				.collect(),
+			b1: vec![0.0; HIDDEN_SIZE],
+			w2: (0..HIDDEN_SIZE).map(|_| rng.gen_range(-0.1, 0.1)).collect(),
+			b2: 0.0
+		}
+	}
+
+	/// One step of gradient descent on the squared error between this
+	/// position's value estimate and the observed outcome (+1 if the side
+	/// to move went on to win, -1 otherwise), backpropagated through the
+	/// hidden layer's ReLU units into `w1`/`b1` as well as `w2`/`b2`.
+	pub fn train_on_outcome(&mut self, features: &[f32; FEATURE_COUNT], won: bool, learning_rate: f32) {
+		let target = if won { 1.0 } else { -1.0 };
+		let (value, hidden) = self.forward_with_hidden(features);
+		let error = target - value;
+
+		self.b2 += learning_rate * error;
+		for h in 0..HIDDEN_SIZE {
+			// ReLU units that didn't fire pass no gradient upstream.
+			if hidden[h] > 0.0 {
+				let delta = learning_rate * error * self.w2[h];
+				self.b1[h] += delta;
+				for i in 0..FEATURE_COUNT {
+					self.w1[h][i] += delta * features[i];
+				}
+			}
+			self.w2[h] += learning_rate * error * hidden[h];
+		}
+	}
+
+	/// Forward pass that also returns the hidden layer's post-ReLU
+	/// activations, needed by `train_on_outcome` to backpropagate.
+	fn forward_with_hidden(&self, features: &[f32; FEATURE_COUNT]) -> (f32, [f32; HIDDEN_SIZE]) {
+		let mut hidden = [0.0; HIDDEN_SIZE];
+		let mut value = self.b2;
+		for h in 0..HIDDEN_SIZE {
+			let mut sum = self.b1[h];
+			for i in 0..FEATURE_COUNT {
+				sum += self.w1[h][i] * features[i];
+			}
+			hidden[h] = sum.max(0.0); // ReLU
+			value += self.w2[h] * hidden[h];
+		}
+		(value, hidden)
+	}
+
+	fn forward(&self, features: &[f32; FEATURE_COUNT]) -> f32 {
+		self.forward_with_hidden(features).0
+	}
+}
+
+/// Extracts the same board features `Standard` reasons about - column
+/// heights, bumpiness, holes, outstanding garbage - plus the combo/B2B state
+/// from the scoring subsystem, for the network to consume.
+pub(crate) fn features(board: &Board<ColoredRow>, garbage_queue: u32, combo: i32, back_to_back: bool) -> [f32; FEATURE_COUNT] {
+	let mut out = [0.0; FEATURE_COUNT];
+	let heights = board.column_heights();
+	for i in 0..10 {
+		out[i] = heights[i] as f32;
+	}
+	for i in 0..9 {
+		out[10 + i] = (heights[i] - heights[i + 1]).abs() as f32;
+	}
+	out[19] = board.hole_count() as f32;
+	out[20] = garbage_queue as f32;
+	out[21] = combo.max(0) as f32;
+	out[22] = if back_to_back { 1.0 } else { 0.0 };
+	out
+}
+
+/// A learned alternative to `Standard`, used to let cold-clear improve its
+/// weights through self-play instead of hand tuning.
+#[derive(Clone)]
+pub struct NnEvaluator {
+	weights: NnWeights,
+	name: String
+}
+
+impl NnEvaluator {
+	pub fn new(weights: NnWeights) -> Self {
+		NnEvaluator { weights, name: "Neural Network".to_owned() }
+	}
+}
+
+impl Evaluator for NnEvaluator {
+	type Value = f32;
+	type Reward = i32;
+
+	fn name(&self) -> String {
+		self.name.clone()
+	}
+
+	fn evaluate(
+		&self,
+		lock: &LockResult,
+		board: &Board<ColoredRow>,
+		garbage_queue: u32,
+		combo: i32,
+		back_to_back: bool,
+		_placed: Piece
+	) -> (Self::Value, Self::Reward) {
+		let value = self.weights.forward(&features(board, garbage_queue, combo, back_to_back));
+		let reward = lock.garbage_sent as i32 - if lock.locked_out { 1000 } else { 0 };
+		(value, reward)
+	}
+}
@@ -0,0 +1,67 @@
+use std::sync::{ Arc, RwLock };
+use crate::battle_tttz::do_training_game;
+use crate::nn_evaluator::{ NnEvaluator, NnWeights };
+
+/// Runs self-play games with a double-buffered weight set: in-flight games
+/// keep reading `active` while `training` is updated towards the observed
+/// outcome, and `active` is replaced with a copy of `training` only between
+/// games so no game ever sees
+/// its network change mid-play.
+pub struct Trainer {
+	active: Arc<RwLock<NnWeights>>,
+	training: NnWeights,
+	learning_rate: f32
+}
+
+impl Trainer {
+	pub fn new(weights: NnWeights, learning_rate: f32) -> Self {
+		Trainer {
+			training: weights.clone(),
+			active: Arc::new(RwLock::new(weights)),
+			learning_rate
+		}
+	}
+
+	pub fn active_weights(&self) -> NnWeights {
+		self.active.read().unwrap().clone()
+	}
+
+	/// Plays one self-play game with the current stable weights on both
+	/// sides, then trains the training copy on every position either side
+	/// actually reached during the game, labelled with whether that side
+	/// went on to win, before publishing it as the new active weights.
+	pub fn train_one_game(&mut self) {
+		let stable = self.active_weights();
+		let p1 = NnEvaluator::new(stable.clone());
+		let p2 = NnEvaluator::new(stable);
+
+		let (positions1, positions2, p1_won) = match do_training_game(p1, p2) {
+			Some(result) => result,
+			// Both sides topped out on the same tick; no winner to learn from.
+			None => return
+		};
+
+		for features in &positions1 {
+			self.training.train_on_outcome(features, p1_won, self.learning_rate);
+		}
+		for features in &positions2 {
+			self.training.train_on_outcome(features, !p1_won, self.learning_rate);
+		}
+
+		let mut active = self.active.write().unwrap();
+		*active = self.training.clone();
+	}
+
+	pub fn save(&self, path: &std::path::Path) -> std::io::Result<()> {
+		let weights = self.active_weights();
+		let file = std::fs::File::create(path)?;
+		serde_json::to_writer(file, &weights)?;
+		Ok(())
+	}
+
+	pub fn load(path: &std::path::Path, learning_rate: f32) -> std::io::Result<Self> {
+		let file = std::fs::File::open(path)?;
+		let weights = serde_json::from_reader(file)?;
+		Ok(Trainer::new(weights, learning_rate))
+	}
+}